@@ -1,33 +1,114 @@
 extern crate nalgebra;
+extern crate num_traits;
+extern crate rayon;
+#[cfg(feature = "euclid")]
+extern crate euclid;
 
 use nalgebra::{DMat};
+use num_traits::{Zero, NumCast, ToPrimitive};
+use std::ops::{Add, Sub, Mul};
+use std::fmt::Display;
+use rayon::prelude::*;
 
 pub trait SourceValue : Copy {
 	fn as_f64(self) -> f64;
+
+	/// Casts this source value into the accumulator type `A` used to build a summed area
+	/// table. Defaults to going through `as_f64` and then `NumCast`, which is exact only
+	/// where the `f64` value is also representable in `A`: an integer `A` truncates a
+	/// fractional value toward zero, an `f32` `A` rounds it to `f32` precision, and a value
+	/// that `NumCast` finds out of range for `A` panics.
+	fn as_accum<A: NumCast>(self) -> A {
+		NumCast::from(self.as_f64()).expect("source value out of range for accumulator type")
+	}
 }
 
-impl SourceValue for u8 { fn as_f64(self) -> f64 { self as f64 } }
-impl SourceValue for i8 { fn as_f64(self) -> f64 { self as f64 } }
-impl SourceValue for u32 { fn as_f64(self) -> f64 { self as f64 } }
-impl SourceValue for i32 { fn as_f64(self) -> f64 { self as f64 } }
-impl SourceValue for u64 { fn as_f64(self) -> f64 { self as f64 } }
-impl SourceValue for i64 { fn as_f64(self) -> f64 { self as f64 } }
-impl SourceValue for usize { fn as_f64(self) -> f64 { self as f64 } }
-impl SourceValue for isize { fn as_f64(self) -> f64 { self as f64 } }
+// Integer source types override `as_accum` to cast straight into `A` via `NumCast`
+// instead of going through the default `as_f64` bounce, which would already lose
+// precision above 2^53 before any accumulation happens.
+impl SourceValue for u8 {
+	fn as_f64(self) -> f64 { self as f64 }
+	fn as_accum<A: NumCast>(self) -> A { NumCast::from(self).expect("source value out of range for accumulator type") }
+}
+impl SourceValue for i8 {
+	fn as_f64(self) -> f64 { self as f64 }
+	fn as_accum<A: NumCast>(self) -> A { NumCast::from(self).expect("source value out of range for accumulator type") }
+}
+impl SourceValue for u32 {
+	fn as_f64(self) -> f64 { self as f64 }
+	fn as_accum<A: NumCast>(self) -> A { NumCast::from(self).expect("source value out of range for accumulator type") }
+}
+impl SourceValue for i32 {
+	fn as_f64(self) -> f64 { self as f64 }
+	fn as_accum<A: NumCast>(self) -> A { NumCast::from(self).expect("source value out of range for accumulator type") }
+}
+impl SourceValue for u64 {
+	fn as_f64(self) -> f64 { self as f64 }
+	fn as_accum<A: NumCast>(self) -> A { NumCast::from(self).expect("source value out of range for accumulator type") }
+}
+impl SourceValue for i64 {
+	fn as_f64(self) -> f64 { self as f64 }
+	fn as_accum<A: NumCast>(self) -> A { NumCast::from(self).expect("source value out of range for accumulator type") }
+}
+impl SourceValue for usize {
+	fn as_f64(self) -> f64 { self as f64 }
+	fn as_accum<A: NumCast>(self) -> A { NumCast::from(self).expect("source value out of range for accumulator type") }
+}
+impl SourceValue for isize {
+	fn as_f64(self) -> f64 { self as f64 }
+	fn as_accum<A: NumCast>(self) -> A { NumCast::from(self).expect("source value out of range for accumulator type") }
+}
 impl SourceValue for f32 { fn as_f64(self) -> f64 { self as f64 } }
 impl SourceValue for f64 { fn as_f64(self) -> f64 { self } }
 
-impl <'a>SourceValue for &'a u8 { fn as_f64(self) -> f64 { *self as f64 } }
-impl <'a>SourceValue for &'a i8 { fn as_f64(self) -> f64 { *self as f64 } }
-impl <'a>SourceValue for &'a u32 { fn as_f64(self) -> f64 { *self as f64 } }
-impl <'a>SourceValue for &'a i32 { fn as_f64(self) -> f64 { *self as f64 } }
-impl <'a>SourceValue for &'a u64 { fn as_f64(self) -> f64 { *self as f64 } }
-impl <'a>SourceValue for &'a i64 { fn as_f64(self) -> f64 { *self as f64 } }
-impl <'a>SourceValue for &'a usize { fn as_f64(self) -> f64 { *self as f64 } }
-impl <'a>SourceValue for &'a isize { fn as_f64(self) -> f64 { *self as f64 } }
+impl <'a>SourceValue for &'a u8 {
+	fn as_f64(self) -> f64 { *self as f64 }
+	fn as_accum<A: NumCast>(self) -> A { NumCast::from(*self).expect("source value out of range for accumulator type") }
+}
+impl <'a>SourceValue for &'a i8 {
+	fn as_f64(self) -> f64 { *self as f64 }
+	fn as_accum<A: NumCast>(self) -> A { NumCast::from(*self).expect("source value out of range for accumulator type") }
+}
+impl <'a>SourceValue for &'a u32 {
+	fn as_f64(self) -> f64 { *self as f64 }
+	fn as_accum<A: NumCast>(self) -> A { NumCast::from(*self).expect("source value out of range for accumulator type") }
+}
+impl <'a>SourceValue for &'a i32 {
+	fn as_f64(self) -> f64 { *self as f64 }
+	fn as_accum<A: NumCast>(self) -> A { NumCast::from(*self).expect("source value out of range for accumulator type") }
+}
+impl <'a>SourceValue for &'a u64 {
+	fn as_f64(self) -> f64 { *self as f64 }
+	fn as_accum<A: NumCast>(self) -> A { NumCast::from(*self).expect("source value out of range for accumulator type") }
+}
+impl <'a>SourceValue for &'a i64 {
+	fn as_f64(self) -> f64 { *self as f64 }
+	fn as_accum<A: NumCast>(self) -> A { NumCast::from(*self).expect("source value out of range for accumulator type") }
+}
+impl <'a>SourceValue for &'a usize {
+	fn as_f64(self) -> f64 { *self as f64 }
+	fn as_accum<A: NumCast>(self) -> A { NumCast::from(*self).expect("source value out of range for accumulator type") }
+}
+impl <'a>SourceValue for &'a isize {
+	fn as_f64(self) -> f64 { *self as f64 }
+	fn as_accum<A: NumCast>(self) -> A { NumCast::from(*self).expect("source value out of range for accumulator type") }
+}
 impl <'a>SourceValue for &'a f32 { fn as_f64(self) -> f64 { *self as f64 } }
 impl <'a>SourceValue for &'a f64 { fn as_f64(self) -> f64 { *self } }
 
+/// A raw pointer wrapper asserting it is safe to share across rayon's worker threads.
+/// Only used by `calculate_full_summed_area_table_parallel`, where each thread is given
+/// disjoint indices to write through it.
+struct RawMutPtr<A>(*mut A);
+unsafe impl <A> Sync for RawMutPtr<A> {}
+
+/// A numeric type usable as the accumulator backing a `SummedAreaTable`. `f64` is the
+/// crate's historical default (see `SummedAreaTable`'s default type parameter), but
+/// integer types such as `u64` keep sums exact, and `f32` halves memory use for large
+/// tables where that precision is enough.
+pub trait Accumulator: Copy + Zero + PartialOrd + Display + Add<Output=Self> + Sub<Output=Self> + Mul<Output=Self> + NumCast + ToPrimitive {}
+impl <A: Copy + Zero + PartialOrd + Display + Add<Output=A> + Sub<Output=A> + Mul<Output=A> + NumCast + ToPrimitive> Accumulator for A {}
+
 /// This trait represents the source for a summed area table.
 /// Implement this trait for a type to use it as data source for a summed area table.
 pub trait SummedAreaTableSource<T: SourceValue>{
@@ -39,8 +120,10 @@ pub trait SummedAreaTableSource<T: SourceValue>{
 
 	/// Calculates and returns the actual summed area table for a given rect.
 	/// The arguments 'from' and 'to' represent the rects top-left (inclusive) and bottom-right (inclusive) point.
-	fn calculate_summed_area_table(&self, from: (usize, usize), to: (usize, usize)) -> SummedAreaTable{
-		let mut table:DMat<f64> = DMat::new_zeros(self.height(),self.width());
+	/// `A` is the accumulator type the table is built in; it is inferred from the type the
+	/// result is used as (e.g. a binding annotated as the bare `SummedAreaTable` gives `f64`).
+	fn calculate_summed_area_table<A: Accumulator>(&self, from: (usize, usize), to: (usize, usize)) -> SummedAreaTable<A>{
+		let mut table:DMat<A> = DMat::from_elem(self.height(), self.width(), A::zero());
 
 		let (from_x, from_y) = from;
 		let (to_x, to_y) = to;
@@ -48,7 +131,7 @@ pub trait SummedAreaTableSource<T: SourceValue>{
 		for row in from_y .. to_y+1 {
 			for col in from_x .. to_x+1 {
 
-				let mut sum = self.at(col, row).as_f64();
+				let mut sum = self.at(col, row).as_accum::<A>();
 
 				if row>0 {
 					sum = sum + table[(row-1, col)];
@@ -63,23 +146,125 @@ pub trait SummedAreaTableSource<T: SourceValue>{
 			}
 		}
 
-		SummedAreaTable{table: table}
+		SummedAreaTable{table: table, sqsum_table: None}
 	}
 
 	/// Calculates and returns the actual summed area table for the whole source matrix.
-	fn calculate_full_summed_area_table(&self) -> SummedAreaTable{
+	fn calculate_full_summed_area_table<A: Accumulator>(&self) -> SummedAreaTable<A>{
 		let ncols= self.width();
 		let nrows= self.height();
 		self.calculate_summed_area_table((0,0),(ncols-1, nrows-1))
 	}
+
+	/// Calculates the summed area table together with a second integral image of the
+	/// squared source values, in a single pass over the whole source matrix.
+	/// The squared table enables O(1) variance/standard-deviation queries, see
+	/// `SummedAreaTable::get_variance` and `SummedAreaTable::get_std_dev`.
+	fn calculate_full_sum_and_sqsum_table<A: Accumulator>(&self) -> SummedAreaTable<A>{
+		let ncols = self.width();
+		let nrows = self.height();
+
+		let mut table:DMat<A> = DMat::from_elem(nrows, ncols, A::zero());
+		let mut sqsum_table:DMat<A> = DMat::from_elem(nrows, ncols, A::zero());
+
+		for row in 0 .. nrows {
+			for col in 0 .. ncols {
+
+				let value = self.at(col, row).as_accum::<A>();
+				let mut sum = value;
+				let mut sqsum = value * value;
+
+				if row>0 {
+					sum = sum + table[(row-1, col)];
+					sqsum = sqsum + sqsum_table[(row-1, col)];
+				}
+				if col>0 {
+					sum = sum + table[(row, col-1)];
+					sqsum = sqsum + sqsum_table[(row, col-1)];
+				}
+				if row>0 && col>0 {
+					sum = sum - table[(row-1, col-1)];
+					sqsum = sqsum - sqsum_table[(row-1, col-1)];
+				}
+				table[(row,col)] = sum;
+				sqsum_table[(row,col)] = sqsum;
+			}
+		}
+
+		SummedAreaTable{table: table, sqsum_table: Some(sqsum_table)}
+	}
+
+	/// Builds the full summed area table the same way `calculate_full_summed_area_table`
+	/// does, but exploits the separability of the integral image to do it with rayon
+	/// across two passes instead of one strictly serial scan. Pass one computes, for
+	/// each row independently, the horizontal running sum so cell `(row,col)` holds the
+	/// sum of `source[row][0..=col]`. Pass two then computes, for each column
+	/// independently, the vertical running sum down that column in place, turning
+	/// `(row,col)` into the sum over the whole `(0,0)..(row,col)` rectangle. Both passes
+	/// share one buffer; pass two must not start until pass one has fully completed, but
+	/// within a single pass each row (or column) touches disjoint memory. For an integer
+	/// `Accumulator` addition is associative, so the result is bit-identical to the
+	/// serial version; for a floating-point `Accumulator` it is only numerically
+	/// equivalent, since reordering the additions (horizontal-then-vertical here, versus
+	/// the serial version's inclusion-exclusion recurrence) can change rounding once
+	/// values span a wide magnitude range.
+	fn calculate_full_summed_area_table_parallel<A: Accumulator + Send>(&self) -> SummedAreaTable<A>
+		where Self: Sync, T: Sync
+	{
+		let ncols = self.width();
+		let nrows = self.height();
+
+		let mut buffer: Vec<A> = vec![A::zero(); nrows*ncols];
+
+		// Pass 1: the horizontal running sum of a row only depends on that row, so every
+		// row is an independent, contiguous chunk of `buffer` that rayon can hand to a
+		// different thread.
+		buffer.par_chunks_mut(ncols).enumerate().for_each(|(row, row_buf)| {
+			let mut sum = A::zero();
+			for col in 0 .. ncols {
+				sum = sum + self.at(col, row).as_accum::<A>();
+				row_buf[col] = sum;
+			}
+		});
+
+		// Pass 2: the vertical running sum of a column only depends on the (already
+		// row-summed) values in that same column. Columns are not contiguous in
+		// `buffer`, but the indices `row*ncols+col` touched by different columns never
+		// overlap, so it is sound for each column's thread to mutate them concurrently
+		// through a raw pointer.
+		let ptr = RawMutPtr(buffer.as_mut_ptr());
+		(0 .. ncols).into_par_iter().for_each(|col| {
+			let mut sum = A::zero();
+			for row in 0 .. nrows {
+				unsafe {
+					let cell = ptr.0.add(row*ncols + col);
+					sum = sum + *cell;
+					*cell = sum;
+				}
+			}
+		});
+
+		let table: DMat<A> = DMat::from_row_vec(nrows, ncols, &buffer[..]);
+		SummedAreaTable{table: table, sqsum_table: None}
+	}
 }
 
 /// This struct represents the result of a summed area table calculation.
-pub struct SummedAreaTable {
-	pub table: DMat<f64>,
+/// `A` defaults to `f64`, but that default only applies where `SummedAreaTable` is
+/// written out as a bare type (e.g. `let table: SummedAreaTable = ...;`); Rust does not
+/// fall back to a struct's default type parameter to resolve an otherwise-unconstrained
+/// inference variable, so a `let table = src.calculate_full_summed_area_table();`
+/// binding whose only later use doesn't pin `A` to a concrete type (comparing a sum
+/// against an `f64` literal does; reading `table.table.nrows()` alone does not) will
+/// fail to compile with "type annotations needed" unless annotated.
+pub struct SummedAreaTable<A: Accumulator = f64> {
+	pub table: DMat<A>,
+	/// Integral image of the squared source values. Only populated when the table
+	/// was built via `calculate_full_sum_and_sqsum_table`; required by `get_variance`/`get_std_dev`.
+	pub sqsum_table: Option<DMat<A>>,
 }
 
-impl SummedAreaTable {
+impl <A: Accumulator> SummedAreaTable<A> {
 
 	/// Returns the sum for a given area,
 	/// that is described by its upper left and lower right point.
@@ -87,7 +272,7 @@ impl SummedAreaTable {
 	/// It will panic in debug mode if `from` is right of or below `to`.
 	/// `from` is a x/y coordinate tuple for the upper left point (inclusive)
 	/// `to` is a x/y coordinate tuple for the lower right point (inclusive)
-	pub fn get_sum(&self, from: (usize,usize), to: (usize,usize)) -> f64{
+	pub fn get_sum(&self, from: (usize,usize), to: (usize,usize)) -> A{
 		let (col1, row1) = from;
 		let (col2, row2) = to;
 
@@ -132,11 +317,96 @@ impl SummedAreaTable {
 	/// `from` is a x/y coordinate tuple for the upper left point (inclusive)
 	/// `to` is a x/y coordinate tuple for the lower right point (inclusive)
 	pub fn get_average(&self, from: (usize,usize), to: (usize,usize))-> f64{
-		let sum = self.get_sum(from,to);
+		let sum = self.get_sum(from,to).to_f64().expect("accumulator value out of range for f64");
 		let data_count = self.get_data_count(from, to);
 		sum/data_count as f64
 	}
 
+	/// Returns the variance for a given area,
+	/// that is described by its upper left and lower right point.
+	/// Requires the table to have been built via `calculate_full_sum_and_sqsum_table`;
+	/// it will panic (in debug mode *and* release mode) otherwise.
+	/// It will panic in debug mode if the given points are not within the tables bounds.
+	/// It will panic in debug mode if `from` is right of or below `to`.
+	/// `from` is a x/y coordinate tuple for the upper left point (inclusive)
+	/// `to` is a x/y coordinate tuple for the lower right point (inclusive)
+	pub fn get_variance(&self, from: (usize,usize), to: (usize,usize)) -> f64{
+		let n = self.get_data_count(from, to) as f64;
+		let sum = self.get_sum(from, to).to_f64().expect("accumulator value out of range for f64");
+		let sqsum = self.get_sqsum(from, to).to_f64().expect("accumulator value out of range for f64");
+		let mean = sum / n;
+		let variance = sqsum / n - mean*mean;
+
+		// Clamp away tiny negative values caused by floating-point error.
+		if variance < 0.0 { 0.0 } else { variance }
+	}
+
+	/// Returns the standard deviation for a given area,
+	/// that is described by its upper left and lower right point.
+	/// Requires the table to have been built via `calculate_full_sum_and_sqsum_table`;
+	/// it will panic (in debug mode *and* release mode) otherwise.
+	/// It will panic in debug mode if the given points are not within the tables bounds.
+	/// It will panic in debug mode if `from` is right of or below `to`.
+	/// `from` is a x/y coordinate tuple for the upper left point (inclusive)
+	/// `to` is a x/y coordinate tuple for the lower right point (inclusive)
+	pub fn get_std_dev(&self, from: (usize,usize), to: (usize,usize)) -> f64{
+		self.get_variance(from, to).sqrt()
+	}
+
+	/// Returns the variance for the whole area.
+	pub fn get_overall_variance(&self) -> f64{
+		self.get_variance((0,0),(self.table.ncols()-1,self.table.nrows()-1))
+	}
+
+	/// Returns the standard deviation for the whole area.
+	pub fn get_overall_std_dev(&self) -> f64{
+		self.get_std_dev((0,0),(self.table.ncols()-1,self.table.nrows()-1))
+	}
+
+	/// Returns the sum of squared source values for a given area, the same way `get_sum`
+	/// does for the plain values. Requires `sqsum_table` to be populated; panics (in
+	/// debug mode *and* release mode) otherwise.
+	/// It will panic in debug mode if the given points are not within the tables bounds.
+	/// It will panic in debug mode if `from` is right of or below `to`.
+	fn get_sqsum(&self, from: (usize,usize), to: (usize,usize)) -> A{
+		let (col1, row1) = from;
+		let (col2, row2) = to;
+
+		let sqsum_table = self.sqsum_table.as_ref().expect(
+			"get_variance/get_std_dev require a table built via calculate_full_sum_and_sqsum_table");
+
+		debug_assert!(row1 <= row2 && col1 <= col2, "`from` ({}/{}) must not be right of or below `to`({}/{})", col1, row1, col2, row2);
+
+		debug_assert!( {
+			let ncols = sqsum_table.ncols();
+			let nrows = sqsum_table.nrows();
+			col1 < ncols && col2 < ncols && row1 < nrows && row2 < nrows
+		},"`from` ({}/{}) or `to` ({}/{}) not within table bounds [(0/0)..({}/{})]", col1, row1, col2, row2, sqsum_table.ncols()-1, sqsum_table.nrows()-1);
+
+		let mut sqsum = sqsum_table[(row2,col2)];
+
+		if col1 > 0 && row1 > 0 {
+			sqsum = sqsum + sqsum_table[(row1-1,col1-1)];
+		}
+		if col1 > 0 {
+			let temp = sqsum_table[(row2,col1-1)];
+
+			debug_assert!(temp<=sqsum, "Overlow-Alarm 1: p1({}/{}) p2({}/{}) temp({}) sum({})",
+			col1, row1, col2, row2, temp, sqsum);
+
+			sqsum = sqsum - temp;
+		}
+		if row1 > 0 {
+			let temp = sqsum_table[(row1-1,col2)];
+
+			debug_assert!(temp<=sqsum, "Overlow-Alarm 2: p1({}/{}) p2({}/{}) temp({}) sum({}) ",
+			col1, row1, col2, row2, temp, sqsum);
+
+			sqsum = sqsum - temp;
+		}
+		sqsum
+	}
+
 	/// Returns the number of data points at the given area.
 	pub fn get_data_count(&self, from: (usize,usize), to: (usize,usize))-> usize{
 		let (from_x, from_y) = from;
@@ -160,7 +430,7 @@ impl SummedAreaTable {
 	/// It will panic in debug mode if `from` is right of or below `to`.
 	/// `from` is a x/y coordinate tuple for the upper left point (inclusive)
 	/// `to` is a x/y coordinate tuple for the lower right point (inclusive)
-	pub fn get_overall_sum(&self) -> f64{
+	pub fn get_overall_sum(&self) -> A{
 		self.get_sum((0,0),(self.table.ncols()-1,self.table.nrows()-1))
 	}
 
@@ -168,6 +438,115 @@ impl SummedAreaTable {
 	pub fn get_overall_data_count(&self) -> usize{
 		self.table.ncols()*self.table.nrows()
 	}
+
+	/// Scans every `w x h` window across the table and returns the top-left coordinate
+	/// and sum of the window with the greatest sum. Each window is a constant-time
+	/// four-corner `get_sum` lookup, so the whole scan is O(width*height) regardless of
+	/// window size. It will panic in debug mode if `w` or `h` is zero or larger than the
+	/// table.
+	pub fn find_max_sum_window(&self, w: usize, h: usize) -> ((usize,usize), A){
+		self.find_max_sum_window_strided(w, h, 1)
+	}
+
+	/// Same as `find_max_sum_window`, but only considers top-left positions that are a
+	/// multiple of `stride` apart on each axis, to subsample the search over large tables.
+	pub fn find_max_sum_window_strided(&self, w: usize, h: usize, stride: usize) -> ((usize,usize), A){
+		let ncols = self.table.ncols();
+		let nrows = self.table.nrows();
+
+		debug_assert!(w > 0 && h > 0, "window size ({}/{}) must not be zero", w, h);
+		debug_assert!(w <= ncols && h <= nrows, "window ({}/{}) must not be larger than the table ({}/{})", w, h, ncols, nrows);
+		debug_assert!(stride > 0, "stride must not be zero");
+
+		let mut best_pos = (0,0);
+		let mut best_sum: Option<A> = None;
+
+		let mut y = 0;
+		while y + h <= nrows {
+			let mut x = 0;
+			while x + w <= ncols {
+				let sum = self.get_sum((x,y),(x+w-1,y+h-1));
+
+				if best_sum.map_or(true, |best| sum > best) {
+					best_sum = Some(sum);
+					best_pos = (x,y);
+				}
+				x += stride;
+			}
+			y += stride;
+		}
+
+		(best_pos, best_sum.expect("table must fit at least one window of the given size"))
+	}
+
+	/// Scans every `w x h` window across the table and returns the top-left coordinate
+	/// and average of the window with the greatest average. See `find_max_sum_window`.
+	pub fn find_max_average_window(&self, w: usize, h: usize) -> ((usize,usize), f64){
+		self.find_max_average_window_strided(w, h, 1)
+	}
+
+	/// Same as `find_max_average_window`, but only considers top-left positions that are
+	/// a multiple of `stride` apart on each axis, to subsample the search over large tables.
+	pub fn find_max_average_window_strided(&self, w: usize, h: usize, stride: usize) -> ((usize,usize), f64){
+		let ncols = self.table.ncols();
+		let nrows = self.table.nrows();
+
+		debug_assert!(w > 0 && h > 0, "window size ({}/{}) must not be zero", w, h);
+		debug_assert!(w <= ncols && h <= nrows, "window ({}/{}) must not be larger than the table ({}/{})", w, h, ncols, nrows);
+		debug_assert!(stride > 0, "stride must not be zero");
+
+		let mut best_pos = (0,0);
+		let mut best_average: Option<f64> = None;
+
+		let mut y = 0;
+		while y + h <= nrows {
+			let mut x = 0;
+			while x + w <= ncols {
+				let average = self.get_average((x,y),(x+w-1,y+h-1));
+
+				if best_average.map_or(true, |best| average > best) {
+					best_average = Some(average);
+					best_pos = (x,y);
+				}
+				x += stride;
+			}
+			y += stride;
+		}
+
+		(best_pos, best_average.expect("table must fit at least one window of the given size"))
+	}
+
+	/// Produces a smaller matrix where each output cell is the average of the
+	/// corresponding `factor_x x factor_y` block of the source, computed in O(1) per
+	/// output cell via `get_average`. Where the source dimensions are not exact
+	/// multiples of the factors, the edge blocks are clipped and averaged over just the
+	/// smaller, clipped area. Output dimensions are `ceil(width/factor_x)` columns by
+	/// `ceil(height/factor_y)` rows. It will panic in debug mode if `factor_x` or
+	/// `factor_y` is zero.
+	pub fn downsample(&self, factor_x: usize, factor_y: usize) -> DMat<f64>{
+		debug_assert!(factor_x > 0 && factor_y > 0, "downsample factors ({}/{}) must not be zero", factor_x, factor_y);
+
+		let ncols = self.table.ncols();
+		let nrows = self.table.nrows();
+
+		let out_cols = (ncols + factor_x - 1) / factor_x;
+		let out_rows = (nrows + factor_y - 1) / factor_y;
+
+		let mut out:DMat<f64> = DMat::new_zeros(out_rows, out_cols);
+
+		for out_row in 0 .. out_rows {
+			for out_col in 0 .. out_cols {
+				let from_x = out_col*factor_x;
+				let from_y = out_row*factor_y;
+				let to_x = (from_x + factor_x - 1).min(ncols-1);
+				let to_y = (from_y + factor_y - 1).min(nrows-1);
+
+				out[(out_row,out_col)] = self.get_average((from_x,from_y),(to_x,to_y));
+			}
+		}
+
+		out
+	}
 }
 
 
@@ -216,25 +595,109 @@ pub mod util {
 	}
 }
 
+/// Integration with `euclid`'s typed 2D geometry, so callers who already work in a
+/// euclid coordinate space can express query regions as a `Box2D` instead of juggling
+/// raw `(x,y)` tuples. Enabled via the `euclid` feature.
+#[cfg(feature = "euclid")]
+pub mod euclid_support {
+	use euclid::{Point2D, Box2D};
+	use {SummedAreaTable, Accumulator};
+
+	impl <A: Accumulator> SummedAreaTable<A> {
+
+		/// Returns the sum for the area described by `rect`, whose `min` corner is
+		/// inclusive and `max` corner is exclusive, matching euclid's own half-open
+		/// `Box2D` convention -- the euclid-typed equivalent of `get_sum`.
+		/// It will panic in debug mode if `rect` is empty (zero width or height), since
+		/// there is then no inclusive bottom-right corner to query.
+		pub fn get_sum_rect<U>(&self, rect: Box2D<usize, U>) -> A {
+			debug_assert!(!rect.is_empty(), "rect must not be empty (zero width or height)");
+			self.get_sum((rect.min.x, rect.min.y), (rect.max.x-1, rect.max.y-1))
+		}
+
+		/// Returns the average for the area described by `rect`, whose `min` corner is
+		/// inclusive and `max` corner is exclusive, matching euclid's own half-open
+		/// `Box2D` convention -- the euclid-typed equivalent of `get_average`.
+		/// It will panic in debug mode if `rect` is empty (zero width or height), since
+		/// there is then no inclusive bottom-right corner to query.
+		pub fn get_average_rect<U>(&self, rect: Box2D<usize, U>) -> f64 {
+			debug_assert!(!rect.is_empty(), "rect must not be empty (zero width or height)");
+			self.get_average((rect.min.x, rect.min.y), (rect.max.x-1, rect.max.y-1))
+		}
+
+		/// Returns the table's full extent as a `Box2D`, so callers can express "the
+		/// whole table" in the same coordinate space as their other queries. `max` is
+		/// exclusive, so it equals the table's width/height, not `width-1`/`height-1`.
+		pub fn full_extent_rect<U>(&self) -> Box2D<usize, U> {
+			Box2D::new(
+				Point2D::new(0, 0),
+				Point2D::new(self.table.ncols(), self.table.nrows()),
+			)
+		}
+	}
+
+	#[cfg(test)]
+	mod tests {
+		use nalgebra::{DMat};
+		use euclid::{Point2D, Box2D};
+		use {SummedAreaTable, SummedAreaTableSource};
+
+		#[test]
+		fn full_extent_round_trips_through_get_sum_rect() {
+			let src: DMat<f64> = DMat::from_elem(10,20,2.0);
+			let table: SummedAreaTable = src.calculate_full_summed_area_table();
+
+			let rect: Box2D<usize, euclid::UnknownUnit> = table.full_extent_rect();
+			assert_eq!(rect.min, Point2D::new(0,0));
+			assert_eq!(rect.max, Point2D::new(20,10));
+
+			assert_eq!(table.get_overall_sum(), table.get_sum_rect(rect));
+			assert_eq!(table.get_overall_average(), table.get_average_rect(rect));
+		}
+
+		#[test]
+		fn get_sum_rect_matches_the_equivalent_inclusive_tuple_query() {
+			let src: DMat<f64> = DMat::from_elem(10,10,3.0);
+			let table: SummedAreaTable = src.calculate_full_summed_area_table();
+
+			// Box2D's max corner is exclusive, so (2,2)..(5,5) covers the same cells as
+			// the inclusive tuple query (2,2)..(4,4).
+			let rect: Box2D<usize, euclid::UnknownUnit> = Box2D::new(Point2D::new(2,2), Point2D::new(5,5));
+			assert_eq!(table.get_sum((2,2),(4,4)), table.get_sum_rect(rect));
+			assert_eq!(table.get_average((2,2),(4,4)), table.get_average_rect(rect));
+		}
+
+		#[test]
+		#[should_panic]
+		fn get_sum_rect_panics_on_empty_rect() {
+			let src: DMat<f64> = DMat::from_elem(10,10,3.0);
+			let table: SummedAreaTable = src.calculate_full_summed_area_table();
+
+			let empty: Box2D<usize, euclid::UnknownUnit> = Box2D::new(Point2D::new(2,2), Point2D::new(2,5));
+			table.get_sum_rect(empty);
+		}
+	}
+}
+
 
 #[test]
 fn zeros() {
 	let src: DMat<usize> = DMat::new_zeros(100,100);
-	let table = src.calculate_full_summed_area_table();
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
 	assert_eq!(0.0, table.get_sum((0,0),(99,99)));
 }
 
 #[test]
 fn ones() {
 	let src: DMat<usize> = DMat::from_elem(100,100,1);
-	let table = src.calculate_full_summed_area_table();
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
 	assert_eq!(10000.0, table.get_sum((0,0),(99,99)));
 }
 
 #[test]
 fn ones_without_first_col_row() {
 	let src: DMat<usize> = DMat::from_elem(100,100,1);
-	let table = src.calculate_full_summed_area_table();
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
 	assert_eq!(10000.0-199.0, table.get_sum((1,1),(99,99)));
 }
 
@@ -242,42 +705,42 @@ fn ones_without_first_col_row() {
 #[test]
 fn twos() {
 	let src: DMat<usize> = DMat::from_elem(100,100,2);
-	let table = src.calculate_full_summed_area_table();
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
 	assert_eq!(20000.0, table.get_sum((0,0),(99,99)));
 }
 
 #[test]
 fn twos_average() {
 	let src: DMat<usize> = DMat::from_elem(3,3,2);
-	let table = src.calculate_full_summed_area_table();
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
 	assert_eq!(2.0, table.get_average((0,0),(2,2)));
 }
 
 #[test]
 fn data_count() {
 	let src: DMat<usize> = DMat::from_elem(123,321,2);
-	let table = src.calculate_full_summed_area_table();
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
 	assert_eq!(123*321, table.get_data_count((0,0),(122,320)));
 }
 
 #[test]
 fn overall_data_count() {
 	let src: DMat<usize> = DMat::from_elem(123,321,2);
-	let table = src.calculate_full_summed_area_table();
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
 	assert_eq!(123*321, table.get_overall_data_count());
 }
 
 #[test]
 fn overall_sum() {
 	let src: DMat<usize> = DMat::from_elem(100,100,2);
-	let table = src.calculate_full_summed_area_table();
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
 	assert_eq!(20000.0, table.get_overall_sum());
 }
 
 #[test]
 fn overall_average() {
 	let src: DMat<usize> = DMat::from_elem(3,3,2);
-	let table = src.calculate_full_summed_area_table();
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
 	assert_eq!(2.0, table.get_overall_average());
 }
 
@@ -285,7 +748,7 @@ fn overall_average() {
 #[test]
 fn ones_quartered() {
 	let src: DMat<usize> = DMat::from_elem(100,100,1);
-	let table = src.calculate_full_summed_area_table();
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
 	assert_eq!(2500.0, table.get_sum((0,0),(49,49)));
 	assert_eq!(2500.0, table.get_sum((50,50),(99,99)));
 	assert_eq!(2500.0, table.get_sum((50,0),(99,49)));
@@ -296,7 +759,7 @@ fn ones_quartered() {
 fn ones_quartered_vec() {
 	let mat = DMat::from_elem(100,100,1);
 	let src = VecSource::new(mat.as_vec(), 100,100);
-	let table = src.calculate_full_summed_area_table();
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
 	assert_eq!(2500.0, table.get_sum((0,0),(49,49)));
 	assert_eq!(2500.0, table.get_sum((50,50),(99,99)));
 	assert_eq!(2500.0, table.get_sum((50,0),(99,49)));
@@ -306,7 +769,7 @@ fn ones_quartered_vec() {
 #[test]
 fn twos_quartered() {
 	let src: DMat<usize> = DMat::from_elem(100,100,2);
-	let table = src.calculate_full_summed_area_table();
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
 	assert_eq!(5000.0, table.get_sum((0,0),(49,49)));
 	assert_eq!(5000.0, table.get_sum((50,50),(99,99)));
 	assert_eq!(5000.0, table.get_sum((50,0),(99,49)));
@@ -316,21 +779,21 @@ fn twos_quartered() {
 #[test]
 fn first_row() {
 	let src: DMat<usize> = DMat::from_elem(10,20,1);
-	let table = src.calculate_full_summed_area_table();
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
 	assert_eq!(20.0, table.get_sum((0,0),(19,0)));
 }
 
 #[test]
 fn first_col() {
 	let src: DMat<usize> = DMat::from_elem(50,100,1);
-	let table = src.calculate_full_summed_area_table();
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
 	assert_eq!(50.0, table.get_sum((0,0),(0,49)));
 }
 
 #[test]
 fn from_to_equal() {
 	let src: DMat<usize> = DMat::from_elem(100,100,1);
-	let table = src.calculate_full_summed_area_table();
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
 	assert_eq!(1.0, table.get_sum((0,0),(0,0)));
 	assert_eq!(1.0, table.get_sum((50,50),(50,50)));
 	assert_eq!(1.0, table.get_sum((99,99),(99,99)));
@@ -354,7 +817,7 @@ fn custom() {
 		15.0,30.0,47.0,62.0,81.0
 	]);
 
-	let table = src.calculate_full_summed_area_table();
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
 
 	for row in 0 .. 5 {
 		for col in 0 .. 5 {
@@ -374,7 +837,7 @@ fn vec_to_dmat() {
 	let src = util::vec_to_dmat(&vec![0,1,2,3,4,5]);
 	assert_eq!(1, src.ncols());
 	assert_eq!(6, src.nrows());
-	let table = src.calculate_full_summed_area_table();
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
 	assert_eq!(6, table.get_overall_data_count());
 	assert_eq!(15.0, table.get_overall_sum());
 }
@@ -382,7 +845,7 @@ fn vec_to_dmat() {
 #[test]
 fn src_and_sat_same_size() {
 	let src: DMat<usize> = DMat::new_zeros(100,100);
-	let table = src.calculate_full_summed_area_table();
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
 	assert_eq!(src.nrows(), table.table.nrows());
 	assert_eq!(src.ncols(), table.table.ncols());
 }
@@ -391,7 +854,7 @@ fn src_and_sat_same_size() {
 #[should_panic]
 fn bound_check_x() {
 	let src: DMat<usize> = DMat::new_zeros(50,100);
-	let table = src.calculate_full_summed_area_table();
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
 	table.get_sum((0,0),(50,99));
 }
 
@@ -399,7 +862,7 @@ fn bound_check_x() {
 #[should_panic]
 fn bound_check_y() {
 	let src: DMat<usize> = DMat::new_zeros(50,100);
-	let table = src.calculate_full_summed_area_table();
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
 	table.get_sum((0,0),(49,100));
 }
 
@@ -407,7 +870,7 @@ fn bound_check_y() {
 #[should_panic]
 fn point_order_check1() {
 	let src: DMat<usize> = DMat::new_zeros(50,100);
-	let table = src.calculate_full_summed_area_table();
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
 	table.get_sum((49,99),(48,98));
 }
 
@@ -415,7 +878,7 @@ fn point_order_check1() {
 #[should_panic]
 fn point_order_check2() {
 	let src: DMat<usize> = DMat::new_zeros(50,100);
-	let table = src.calculate_full_summed_area_table();
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
 	table.get_sum((49,99),(48,99));
 }
 
@@ -423,6 +886,181 @@ fn point_order_check2() {
 #[should_panic]
 fn point_order_check3() {
 	let src: DMat<usize> = DMat::new_zeros(50,100);
-	let table = src.calculate_full_summed_area_table();
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
 	table.get_sum((49,99),(49,98));
 }
+
+#[test]
+fn constant_variance_is_zero() {
+	let src: DMat<usize> = DMat::from_elem(10,10,2);
+	let table: SummedAreaTable = src.calculate_full_sum_and_sqsum_table();
+	assert_eq!(0.0, table.get_overall_variance());
+	assert_eq!(0.0, table.get_overall_std_dev());
+}
+
+#[test]
+fn variance_of_known_values() {
+	// mean = 2, E[x^2] = (0+0+16+16)/4 = 8, variance = 8 - 2^2 = 4, std_dev = 2.
+	let src: DMat<f64> = DMat::from_row_vec(1,4, &[0.0,0.0,4.0,4.0]);
+	let table: SummedAreaTable = src.calculate_full_sum_and_sqsum_table();
+	assert_eq!(4.0, table.get_overall_variance());
+	assert_eq!(2.0, table.get_overall_std_dev());
+}
+
+#[test]
+fn variance_of_subrect() {
+	let src: DMat<f64> = DMat::from_row_vec(4,1, &[2.0,4.0,4.0,4.0]);
+	let table: SummedAreaTable = src.calculate_full_sum_and_sqsum_table();
+	assert_eq!(0.0, table.get_variance((0,1),(0,3)));
+}
+
+#[test]
+fn u64_accumulator_sum_is_exact() {
+	let src: DMat<u64> = DMat::from_elem(100,100,2);
+	let table: SummedAreaTable<u64> = src.calculate_full_summed_area_table();
+	assert_eq!(20000u64, table.get_overall_sum());
+	assert_eq!(2.0, table.get_overall_average());
+}
+
+#[test]
+fn u64_accumulator_preserves_values_above_f64_precision() {
+	// 2^53 + 1 cannot be represented exactly as an f64, so this only round-trips if
+	// `as_accum` casts straight from the source integer into the `u64` accumulator
+	// instead of bouncing through `as_f64` first.
+	let large = (1u64 << 53) + 1;
+	let src: DMat<u64> = DMat::from_elem(1,1,large);
+	let table: SummedAreaTable<u64> = src.calculate_full_summed_area_table();
+	assert_eq!(large, table.get_overall_sum());
+}
+
+#[test]
+fn f32_accumulator_matches_f64() {
+	let src: DMat<usize> = DMat::from_elem(10,10,3);
+	let table: SummedAreaTable<f32> = src.calculate_full_summed_area_table();
+	let table_f64: SummedAreaTable = src.calculate_full_summed_area_table();
+	assert_eq!(300.0f32, table.get_overall_sum());
+	assert_eq!(table_f64.get_overall_sum() as f32, table.get_overall_sum());
+}
+
+#[test]
+fn parallel_matches_serial() {
+	let src: DMat<f64> = DMat::from_row_vec(5,5, &[
+		5.0,2.0,3.0,4.0,1.0,
+		1.0,5.0,4.0,2.0,3.0,
+		2.0,2.0,1.0,3.0,4.0,
+		3.0,5.0,6.0,4.0,5.0,
+		4.0,1.0,3.0,2.0,6.0
+	]);
+	let serial: SummedAreaTable = src.calculate_full_summed_area_table();
+	let parallel: SummedAreaTable = src.calculate_full_summed_area_table_parallel();
+
+	// The two passes reorder additions relative to the serial inclusion-exclusion
+	// recurrence, so for a float accumulator only numeric equivalence is guaranteed,
+	// not bit-identical results -- hence the epsilon rather than `assert_eq!`.
+	for row in 0 .. 5 {
+		for col in 0 .. 5 {
+			let diff = (serial.table[(row,col)] - parallel.table[(row,col)]).abs();
+			assert!(diff < 1e-9, "serial({}) != parallel({}) at ({}/{})", serial.table[(row,col)], parallel.table[(row,col)], row, col);
+		}
+	}
+}
+
+#[test]
+fn parallel_matches_serial_exactly_for_integer_accumulator() {
+	let src: DMat<usize> = DMat::from_elem(20,20,3);
+	let serial: SummedAreaTable<u64> = src.calculate_full_summed_area_table();
+	let parallel: SummedAreaTable<u64> = src.calculate_full_summed_area_table_parallel();
+
+	// Integer addition is associative, so an integer accumulator's result is
+	// bit-identical (here, bit-for-bit equal) regardless of pass ordering.
+	for row in 0 .. 20 {
+		for col in 0 .. 20 {
+			assert_eq!(serial.table[(row,col)], parallel.table[(row,col)]);
+		}
+	}
+}
+
+#[test]
+fn parallel_ones() {
+	let src: DMat<usize> = DMat::from_elem(100,100,1);
+	let table: SummedAreaTable = src.calculate_full_summed_area_table_parallel();
+	assert_eq!(10000.0, table.get_sum((0,0),(99,99)));
+}
+
+#[test]
+fn find_max_sum_window_finds_hottest_region() {
+	let src: DMat<f64> = DMat::from_row_vec(4,4, &[
+		1.0,1.0,1.0,1.0,
+		1.0,9.0,9.0,1.0,
+		1.0,9.0,9.0,1.0,
+		1.0,1.0,1.0,1.0
+	]);
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
+	assert_eq!(((1,1), 36.0), table.find_max_sum_window(2,2));
+}
+
+#[test]
+fn find_max_average_window_finds_hottest_region() {
+	let src: DMat<f64> = DMat::from_row_vec(4,4, &[
+		1.0,1.0,1.0,1.0,
+		1.0,9.0,9.0,1.0,
+		1.0,9.0,9.0,1.0,
+		1.0,1.0,1.0,1.0
+	]);
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
+	assert_eq!(((1,1), 9.0), table.find_max_average_window(2,2));
+}
+
+#[test]
+fn find_max_sum_window_whole_table_is_only_window() {
+	let src: DMat<usize> = DMat::from_elem(3,3,2);
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
+	assert_eq!(((0,0), 18.0), table.find_max_sum_window(3,3));
+}
+
+#[test]
+fn find_max_sum_window_strided_skips_positions() {
+	let src: DMat<f64> = DMat::from_row_vec(4,4, &[
+		1.0,1.0,1.0,1.0,
+		1.0,9.0,9.0,1.0,
+		1.0,9.0,9.0,1.0,
+		1.0,1.0,1.0,1.0
+	]);
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
+	// With a stride of 2 the (1,1) window is skipped, so the best of the remaining
+	// (0,0)/(0,2)/(2,0)/(2,2) windows is reported instead.
+	assert_eq!(((0,0), 12.0), table.find_max_sum_window_strided(2,2,2));
+}
+
+#[test]
+fn downsample_exact_multiple() {
+	let src: DMat<f64> = DMat::from_row_vec(4,4, &[
+		1.0,1.0,2.0,2.0,
+		1.0,1.0,2.0,2.0,
+		3.0,3.0,4.0,4.0,
+		3.0,3.0,4.0,4.0
+	]);
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
+	let down = table.downsample(2,2);
+
+	assert_eq!(2, down.ncols());
+	assert_eq!(2, down.nrows());
+	assert_eq!(1.0, down[(0,0)]);
+	assert_eq!(2.0, down[(0,1)]);
+	assert_eq!(3.0, down[(1,0)]);
+	assert_eq!(4.0, down[(1,1)]);
+}
+
+#[test]
+fn downsample_ragged_edge() {
+	let src: DMat<f64> = DMat::from_row_vec(1,3, &[2.0,4.0,6.0]);
+	let table: SummedAreaTable = src.calculate_full_summed_area_table();
+	let down = table.downsample(2,1);
+
+	// ceil(3/2) = 2 output columns: the first averages {2.0,4.0}, the ragged last one
+	// is just the clipped single remaining column {6.0}.
+	assert_eq!(2, down.ncols());
+	assert_eq!(1, down.nrows());
+	assert_eq!(3.0, down[(0,0)]);
+	assert_eq!(6.0, down[(0,1)]);
+}